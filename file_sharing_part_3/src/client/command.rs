@@ -0,0 +1,61 @@
+use std::{collections::HashSet, error::Error};
+
+use libp2p::{Multiaddr, PeerId};
+use tokio::sync::oneshot;
+
+use crate::network::{Compression, FileManifest};
+
+// Client 与 EventLoop 之间传递的命令
+#[derive(Debug)]
+pub enum Command {
+    // 启动监听指定的多地址
+    StartListening {
+        addr: Multiaddr,
+        sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+    },
+
+    // 拨号连接指定的对等节点
+    Dial {
+        peer_id: PeerId,
+        peer_addr: Multiaddr,
+        sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+    },
+
+    // 在Kademlia上宣告自己提供某个文件，并把文件内容交给事件循环保管，
+    // 以便后续清单/分片请求无需再回到Client。分片响应按`compression`声明的算法
+    // 压缩后再上线，同一算法也写入清单；请求方按每个分片响应自带的压缩标记解压。
+    StartProviding {
+        file_name: String,
+        data: Vec<u8>,
+        compression: Compression,
+        sender: oneshot::Sender<()>,
+    },
+
+    // 查询某个文件的提供者集合，先查本地的公告缓存，缓存没有命中时才发起DHT查询。
+    // DHT以内容哈希而非文件名为键，因此同时带回通过签名公告独立获知的期望根哈希，
+    // 供请求方在信任清单前核对——没有听到过公告就无法给出，返回`None`。
+    GetProviders {
+        file_name: String,
+        sender: oneshot::Sender<(HashSet<PeerId>, Option<[u8; 32]>)>,
+    },
+
+    // 订阅文件可用性公告主题，之后其他节点开始提供文件时会收到广播通知
+    SubscribeAnnouncements {
+        sender: oneshot::Sender<()>,
+    },
+
+    // 向指定对等节点请求文件清单
+    RequestManifest {
+        file_name: String,
+        peer: PeerId,
+        sender: oneshot::Sender<Result<FileManifest, Box<dyn Error + Send>>>,
+    },
+
+    // 向指定对等节点请求某个分片
+    RequestChunk {
+        file_name: String,
+        peer: PeerId,
+        index: u32,
+        sender: oneshot::Sender<Result<Vec<u8>, Box<dyn Error + Send>>>,
+    },
+}
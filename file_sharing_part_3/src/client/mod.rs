@@ -1,14 +1,14 @@
 pub mod command;
 
-use std::{collections::HashSet, error::Error};
+use std::{collections::HashSet, error::Error, io};
 
-use libp2p::{request_response::ResponseChannel, Multiaddr, PeerId};
+use libp2p::{Multiaddr, PeerId};
 use tokio::sync::{
     mpsc::{self, Sender},
     oneshot,
 };
 
-use crate::network::FileResponse;
+use crate::network::{event::Event, merkle_root, Compression, FileManifest};
 
 pub use self::command::Command;
 
@@ -17,11 +17,17 @@ pub use self::command::Command;
 pub struct Client {
     // 将命令发送到mpsc通道
     sender: mpsc::Sender<Command>,
+    // 与EventLoop共用的事件通道，用于汇报下载进度等观察性事件，
+    // 而不是让库函数直接写标准输出
+    event_sender: mpsc::Sender<Event>,
 }
 
 impl Client {
-    pub fn new(sender: Sender<Command>) -> Client {
-        Client { sender }
+    pub fn new(sender: Sender<Command>, event_sender: Sender<Event>) -> Client {
+        Client {
+            sender,
+            event_sender,
+        }
     }
 
     /// 异步启动监听指定的多地址
@@ -83,24 +89,31 @@ impl Client {
 
     /// 异步启动文件提供服务
     ///
-    /// 该函数通过发送命令到文件提供者，启动文件的提供服务它使用异步通道来同步操作，
-    /// 确保命令发送后才会继续执行，以避免在文件提供服务启动前进行其他操作
+    /// 该函数把文件内容交给事件循环保管，并在Kademlia上宣告自己是该文件的提供者。
+    /// 之后收到的清单/分片请求都由事件循环直接依据这份内容作答，不再需要回调Client。
     ///
     /// # 参数
     ///
     /// * `file_name` - 一个字符串，表示需要提供的文件名
+    /// * `data` - 文件的完整二进制内容
+    /// * `compression` - 分片响应时采用的压缩算法，会写入清单，并随每个分片响应携带，供请求方解压
     ///
     /// # 期望
     ///
     /// 期望命令接收者不会被丢弃，以确保命令能够被接收和处理此外，还期望在文件提供服务启动前，
     /// 命令发送者不会被丢弃，以确保命令完整发送
-    pub async fn start_providing(&mut self, file_name: String) {
+    pub async fn start_providing(&mut self, file_name: String, data: Vec<u8>, compression: Compression) {
         // 创建一个一次性通道，用于接收命令执行的结果
         let (sender, receiver) = oneshot::channel();
 
-        // 发送启动文件提供服务的命令，包括文件名和结果接收者
+        // 发送启动文件提供服务的命令，包括文件名、文件内容、压缩算法和结果接收者
         self.sender
-            .send(Command::StartProviding { file_name, sender })
+            .send(Command::StartProviding {
+                file_name,
+                data,
+                compression,
+                sender,
+            })
             .await
             .expect("Command receiver not to be dropped.");
 
@@ -108,21 +121,28 @@ impl Client {
         receiver.await.expect("Sender not to be dropped.");
     }
 
-    /// 异步获取文件提供者集合
+    /// 异步获取文件提供者集合及其独立获知的内容哈希
     ///
     /// 该函数通过发送命令请求来获取指定文件名对应的提供者集合(PeerId的HashSet)。
     /// 它使用一次性通道(oneshot::channel)来接收响应，确保命令处理后能够接收到结果。
+    /// DHT以内容哈希而非文件名为键，事件循环据此先查本地的公告缓存（见
+    /// `subscribe_announcements`），缓存没有命中时才会退回到一次按哈希的DHT查询；
+    /// 如果从未听到过这个文件名对应的签名公告，返回的哈希为`None`，提供者集合为空——
+    /// 内容寻址下这是预期行为，而不是错误。
+    ///
+    /// 返回的哈希就是[`Client::request_file`]所需的`expected_root_hash`：它来自发布者
+    /// 签名的gossipsub公告，独立于之后将要被验证的那个对端返回的控制面清单。
     ///
     /// # 参数
     /// - `file_name`: 需要查询的文件名
     ///
     /// # 返回
-    /// - `HashSet<PeerId>`: 文件的提供者集合
+    /// - `(HashSet<PeerId>, Option<[u8; 32]>)`: 文件的提供者集合，以及（如果已知）其内容哈希
     ///
     /// # 错误处理
     /// - 如果命令接收者被丢弃，`send` 方法会 panic。
     /// - 如果发送者被丢弃，`await` 方法会 panic。
-    pub async fn get_providers(&mut self, file_name: String) -> HashSet<PeerId> {
+    pub async fn get_providers(&mut self, file_name: String) -> (HashSet<PeerId>, Option<[u8; 32]>) {
         // 创建一次性通道，用于接收文件提供者信息
         let (sender, receiver) = oneshot::channel();
 
@@ -136,38 +156,39 @@ impl Client {
         receiver.await.expect("Sender not to be dropped.")
     }
 
-    /// 异步请求文件
-    ///
-    /// 该函数通过发送命令请求从指定的对等端请求文件它使用一次性通道来接收响应
-    /// 主要用于在分布式网络中从其他节点获取文件
-    ///
-    /// # 参数
-    ///
-    /// * `peer` - 指定的对等端ID，表示从哪个节点请求文件
-    /// * `file_name` - 要请求的文件名字符串，表示需要获取的文件的名称
+    /// 订阅文件可用性公告主题
     ///
-    /// # 返回
+    /// 订阅之后，其他节点调用`start_providing`时广播的公告会被投递到事件循环的
+    /// 本地提供者缓存中，`get_providers`会优先查这份缓存，免去每次都去查询DHT。
+    pub async fn subscribe_announcements(&mut self) {
+        let (sender, receiver) = oneshot::channel();
+
+        self.sender
+            .send(Command::SubscribeAnnouncements { sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+
+        receiver.await.expect("Sender not to be dropped.");
+    }
+
+    /// 异步请求文件清单
     ///
-    /// * `Ok(String)` - 如果文件成功接收到，则返回文件内容的字符串
-    /// * `Err(Box<dyn Error + Send>)` - 如果文件接收失败，则返回一个装箱的错误类型
+    /// 在拉取任何分片之前，请求方先获取文件的清单，得知总大小、分片大小、分片数量
+    /// 以及内容摘要，据此驱动后续的分片请求。
     ///
-    /// # 错误
+    /// # 参数
     ///
-    /// 可能的错误包括但不限于：
-    /// * 发送命令时发生错误
-    /// * 接收文件内容时发生错误
-    /// * 对等端或文件不存在
-    pub async fn request_file(
+    /// * `peer` - 指定的对等端ID
+    /// * `file_name` - 要请求的文件名
+    pub async fn request_manifest(
         &mut self,
         peer: PeerId,
         file_name: String,
-    ) -> Result<String, Box<dyn Error + Send>> {
-        // 创建一个一次性通道，用于接收文件内容
+    ) -> Result<FileManifest, Box<dyn Error + Send>> {
         let (sender, receiver) = oneshot::channel();
 
-        // 发送请67求文件的命令，包含文件名、对等端ID和用于接收文件内容的发送端
         self.sender
-            .send(Command::RequestFile {
+            .send(Command::RequestManifest {
                 file_name,
                 peer,
                 sender,
@@ -175,26 +196,179 @@ impl Client {
             .await
             .expect("Command receiver not to be dropped.");
 
-        // 等待并接收文件内容，如果发送端已被丢弃，则返回错误
-        receiver.await.expect("Sender not be dropped.")
+        receiver.await.expect("Sender not to be dropped.")
     }
 
-    #[allow(dead_code)]
-    /// 异步处理文件响应请求
-    ///
-    /// 该函数用于将文件作为响应发送到请求者
-    /// 它通过内部的sender将一个包含文件路径和响应通道的命令发送出去
+    /// 异步请求某一个分片
     ///
     /// # 参数
-    /// - `file`: 一个字符串，表示要响应的文件路径
-    /// - `channel`: 一个响应通道，用于发送文件响应结果
     ///
-    /// # 期望
-    /// 期望命令接收者不会被丢弃如果接收者被丢弃，发送操作将失败，并产生一个panic
-    pub async fn respond_file(&mut self, file: String, channel: ResponseChannel<FileResponse>) {
+    /// * `peer` - 指定的对等端ID
+    /// * `file_name` - 要请求的文件名
+    /// * `index` - 分片序号，从0开始
+    pub async fn request_chunk(
+        &mut self,
+        peer: PeerId,
+        file_name: String,
+        index: u32,
+    ) -> Result<Vec<u8>, Box<dyn Error + Send>> {
+        let (sender, receiver) = oneshot::channel();
+
         self.sender
-            .send(Command::RespondFile { file, channel })
+            .send(Command::RequestChunk {
+                file_name,
+                peer,
+                index,
+                sender,
+            })
             .await
             .expect("Command receiver not to be dropped.");
+
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    // 单个分片哈希校验失败后，最多重新请求的次数
+    const MAX_CHUNK_RETRIES: u32 = 3;
+
+    // 清单来自未必可信的对等端，total_size在被用于`Vec::with_capacity`之前必须先设上限，
+    // 否则一个声明`total_size = u64::MAX`的清单足以让请求方在校验任何字节之前就崩溃。
+    const MAX_TOTAL_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+    // 在信任清单里的任何数值之前做基本校验：total_size不能超出允许的上限，
+    // chunk_count必须与chunk_hashes的实际长度以及total_size/chunk_size换算的结果一致。
+    // 任何一项不符都说明清单被篡改或损坏，直接拒绝，不再往下分配内存或发起分片请求。
+    fn validate_manifest(manifest: &FileManifest) -> Result<(), Box<dyn Error + Send>> {
+        if manifest.chunk_size == 0 {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "清单声明的chunk_size为0",
+            )));
+        }
+
+        if manifest.total_size > Self::MAX_TOTAL_SIZE {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "清单声明的total_size({})超过了允许的上限({})",
+                    manifest.total_size,
+                    Self::MAX_TOTAL_SIZE
+                ),
+            )));
+        }
+
+        if manifest.chunk_hashes.len() != manifest.chunk_count as usize {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "清单的chunk_count与chunk_hashes数量不一致",
+            )));
+        }
+
+        let expected_chunk_count = ((manifest.total_size + manifest.chunk_size as u64 - 1)
+            / manifest.chunk_size as u64)
+            .max(1);
+        if expected_chunk_count != manifest.chunk_count as u64 {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "清单的chunk_count与total_size/chunk_size换算结果不一致",
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// 异步请求文件
+    ///
+    /// 该函数先请求清单，再按照分片数量逐个请求分片并拼接，从而重建完整文件。
+    /// 这样单个文件不再受限于一帧的大小，也不会因为非UTF-8内容而panic。
+    ///
+    /// 清单、其`chunk_hashes`与`root_hash`都来自即将被验证的同一个对端，单靠它们自洽
+    /// 并不能防住恶意提供者连同内容一起伪造清单。因此调用方必须传入`expected_root_hash`
+    /// ——通常取自[`Client::get_providers`]返回的、经发布者签名的公告哈希，是一个独立
+    /// 于这次控制面往返的信任来源。在信任清单的任何其他字段之前，本函数先核对
+    /// `manifest.root_hash`是否与之相符；不符直接拒绝。之后清单中的`chunk_hashes`
+    /// 构成一棵默克尔树：每收到一个分片就立刻核对其BLAKE3哈希，不一致时按
+    /// [`Client::MAX_CHUNK_RETRIES`]重试；全部分片核对通过后，再校验重新计算出的
+    /// 默克尔根是否与`expected_root_hash`一致。
+    ///
+    /// # 参数
+    ///
+    /// * `peer` - 指定的对等端ID，表示从哪个节点请求文件
+    /// * `file_name` - 要请求的文件名字符串，表示需要获取的文件的名称
+    /// * `expected_root_hash` - 独立获知的内容哈希，用于在信任对端清单前先行核对
+    ///
+    /// # 返回
+    ///
+    /// * `Ok(Vec<u8>)` - 如果文件成功接收到且通过完整性校验，则返回完整的文件字节
+    /// * `Err(Box<dyn Error + Send>)` - 如果文件接收失败或完整性校验不通过，则返回一个装箱的错误类型
+    ///
+    /// # 错误
+    ///
+    /// 可能的错误包括但不限于：
+    /// * 发送命令时发生错误
+    /// * 接收文件内容时发生错误
+    /// * 对等端或文件不存在
+    /// * 清单的`root_hash`与`expected_root_hash`不符（对端很可能是恶意或被篡改的）
+    /// * 分片内容与清单中的哈希重试多次后仍不一致，或重组后的默克尔根与清单不符
+    pub async fn request_file(
+        &mut self,
+        peer: PeerId,
+        file_name: String,
+        expected_root_hash: [u8; 32],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send>> {
+        let manifest = self.request_manifest(peer, file_name.clone()).await?;
+
+        // 在信任清单的任何其他字段之前，先核对其根哈希是否与独立来源相符——
+        // 否则对端可以连同`chunk_hashes`/`root_hash`一起伪造，让后面的自洽校验全部通过
+        if manifest.root_hash != expected_root_hash {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "清单的root_hash与独立获知的期望哈希不符，拒绝信任该对端",
+            )));
+        }
+
+        Self::validate_manifest(&manifest)?;
+
+        let mut file = Vec::with_capacity(manifest.total_size as usize);
+        let mut chunk_hashes = Vec::with_capacity(manifest.chunk_count as usize);
+        for index in 0..manifest.chunk_count {
+            let mut attempt = 0;
+            let chunk = loop {
+                let chunk = self.request_chunk(peer, file_name.clone(), index).await?;
+                let hash = *blake3::hash(&chunk).as_bytes();
+                if hash == manifest.chunk_hashes[index as usize] {
+                    break chunk;
+                }
+
+                attempt += 1;
+                if attempt > Self::MAX_CHUNK_RETRIES {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("分片{index}的哈希经{}次重试后仍与清单不符", Self::MAX_CHUNK_RETRIES),
+                    )));
+                }
+            };
+
+            chunk_hashes.push(*blake3::hash(&chunk).as_bytes());
+            file.extend_from_slice(&chunk);
+
+            // 汇报下载进度，方便在传输大文件时观察是否卡住；走与`InboundRequest`相同的
+            // 事件通道，由嵌入方决定如何展示/记录，库本身不直接写标准输出。
+            // 用`try_send`而非`send().await`：事件通道只有一个缓冲位，进度事件仅供观察，
+            // 一旦通道满了就丢弃这次汇报，而不是让下载阻塞在等待嵌入方取走事件上。
+            let _ = self.event_sender.try_send(Event::DownloadProgress {
+                file_name: file_name.clone(),
+                index,
+                chunk_count: manifest.chunk_count,
+            });
+        }
+
+        if merkle_root(&chunk_hashes) != manifest.root_hash {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "重组后的默克尔根与清单不符，文件可能已被篡改或损坏",
+            )));
+        }
+
+        Ok(file)
     }
 }
@@ -1,37 +1,67 @@
 use libp2p::{
+    gossipsub::{Gossipsub, GossipsubEvent},
     kad::{store::MemoryStore, Kademlia, KademliaEvent},
+    mdns::{Mdns, MdnsEvent},
     request_response::{RequestResponse, RequestResponseEvent},
+    swarm::behaviour::toggle::Toggle,
     NetworkBehaviour,
 };
 
-use super::protocol::{FileExchangeCodec, FileRequest, FileResponse};
+use super::protocol::{
+    ChunkRequest, ChunkResponse, ControlCodec, ControlRequest, ControlResponse, DataCodec,
+};
 
-// 组合Kademlia和请求-响应协议
+// 组合Kademlia、控制面请求-响应协议、数据面请求-响应协议、mDNS局域网发现与gossipsub广播
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "ComposedEvent")]
 pub struct ComposedBehaviour {
-    
-    // 用于请求-响应协议的行为
-    pub request_response: RequestResponse<FileExchangeCodec>,
-    
+
+    // 控制面：清单等轻量请求，独立于数据面，不受批量传输排队影响
+    pub control: RequestResponse<ControlCodec>,
+
+    // 数据面：分片等批量传输，libp2p会为其开启独立的子流
+    pub data: RequestResponse<DataCodec>,
+
     // 用于Kademlia协议的行为
     pub kademlia: Kademlia<MemoryStore>,
+
+    // 局域网节点发现，通过`network::new`的参数决定是否启用。
+    // 使用Toggle包装是因为NetworkBehaviour要求字段始终存在，而Toggle可以在运行时关闭内层行为。
+    pub mdns: Toggle<Mdns>,
+
+    // 文件可用性公告的发布/订阅通道，新提供的文件会广播给订阅了公告主题的节点
+    pub gossipsub: Gossipsub,
 }
 
 // 网络行为事件
 #[derive(Debug)]
 pub enum ComposedEvent {
-    
-    // 请求-响应协议事件
-    RequestResponse(RequestResponseEvent<FileRequest, FileResponse>),
-    
+
+    // 控制面协议事件
+    Control(RequestResponseEvent<ControlRequest, ControlResponse>),
+
+    // 数据面协议事件
+    Data(RequestResponseEvent<ChunkRequest, ChunkResponse>),
+
     // Kademlia协议事件
     Kademlia(KademliaEvent),
+
+    // mDNS发现事件
+    Mdns(MdnsEvent),
+
+    // gossipsub发布/订阅事件
+    Gossipsub(GossipsubEvent),
 }
 
-impl From<RequestResponseEvent<FileRequest, FileResponse>> for ComposedEvent {
-    fn from(event: RequestResponseEvent<FileRequest, FileResponse>) -> Self {
-        ComposedEvent::RequestResponse(event)
+impl From<RequestResponseEvent<ControlRequest, ControlResponse>> for ComposedEvent {
+    fn from(event: RequestResponseEvent<ControlRequest, ControlResponse>) -> Self {
+        ComposedEvent::Control(event)
+    }
+}
+
+impl From<RequestResponseEvent<ChunkRequest, ChunkResponse>> for ComposedEvent {
+    fn from(event: RequestResponseEvent<ChunkRequest, ChunkResponse>) -> Self {
+        ComposedEvent::Data(event)
     }
 }
 
@@ -40,3 +70,15 @@ impl From<KademliaEvent> for ComposedEvent {
         ComposedEvent::Kademlia(event)
     }
 }
+
+impl From<MdnsEvent> for ComposedEvent {
+    fn from(event: MdnsEvent) -> Self {
+        ComposedEvent::Mdns(event)
+    }
+}
+
+impl From<GossipsubEvent> for ComposedEvent {
+    fn from(event: GossipsubEvent) -> Self {
+        ComposedEvent::Gossipsub(event)
+    }
+}
@@ -0,0 +1,469 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+use futures::StreamExt;
+use libp2p::{
+    gossipsub::{GossipsubEvent, IdentTopic},
+    kad::{GetProvidersOk, KademliaEvent, QueryId, QueryResult},
+    mdns::MdnsEvent,
+    multiaddr::Protocol,
+    request_response::{RequestId, RequestResponseEvent, RequestResponseMessage},
+    swarm::{Swarm, SwarmEvent},
+    Multiaddr, PeerId,
+};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::client::Command;
+
+use super::{
+    behaviour::{ComposedBehaviour, ComposedEvent},
+    protocol::{
+        merkle_root, Announcement, ChunkRequest, ChunkResponse, Compression, ControlRequest,
+        ControlResponse, FileManifest, ANNOUNCEMENTS_TOPIC, CHUNK_SIZE,
+    },
+};
+
+// 事件循环对外广播的事件，目前仅用于观察，不要求上层做出响应
+#[derive(Debug)]
+pub enum Event {
+    // 收到了一次入站的文件请求（清单或分片）
+    InboundRequest { file_name: String },
+    // `Client::request_file`每收到并校验通过一个分片就汇报一次下载进度，
+    // 供嵌入方按需展示/记录，而不是由库直接写到标准输出
+    DownloadProgress {
+        file_name: String,
+        index: u32,
+        chunk_count: u32,
+    },
+}
+
+// 本地正在提供的一个文件：保存其字节内容，以及可以直接复用的清单
+struct ProvidedFile {
+    data: Vec<u8>,
+    manifest: FileManifest,
+}
+
+fn build_manifest(data: &[u8], compression: Compression) -> FileManifest {
+    let chunk_count = ((data.len() as u64) + CHUNK_SIZE as u64 - 1) / CHUNK_SIZE as u64;
+    let chunk_count = chunk_count.max(1) as u32;
+
+    // 按分片逐一计算BLAKE3哈希作为默克尔树的叶子，之后请求方每收到一个分片
+    // 就能独立核对，不必等到整份文件下载完才发现内容被篡改或损坏
+    let chunk_hashes: Vec<[u8; 32]> = (0..chunk_count)
+        .map(|index| {
+            let start = index as usize * CHUNK_SIZE as usize;
+            let end = (start + CHUNK_SIZE as usize).min(data.len());
+            *blake3::hash(&data[start..end]).as_bytes()
+        })
+        .collect();
+    let root_hash = merkle_root(&chunk_hashes);
+
+    FileManifest {
+        total_size: data.len() as u64,
+        chunk_size: CHUNK_SIZE,
+        // 空文件也至少占用一个（空）分片，方便请求方统一处理
+        chunk_count,
+        chunk_hashes,
+        root_hash,
+        compression,
+    }
+}
+
+// 驱动libp2p Swarm运行的事件循环，负责把Client发来的命令转换成网络行为，
+// 并把网络事件转换成Client可以理解的结果
+pub struct EventLoop {
+    swarm: Swarm<ComposedBehaviour>,
+    command_receiver: mpsc::Receiver<Command>,
+    event_sender: mpsc::Sender<Event>,
+    pending_dial: HashMap<PeerId, oneshot::Sender<Result<(), Box<dyn Error + Send>>>>,
+    pending_start_providing: HashMap<QueryId, oneshot::Sender<()>>,
+    pending_get_providers:
+        HashMap<QueryId, (oneshot::Sender<(HashSet<PeerId>, Option<[u8; 32]>)>, Option<[u8; 32]>)>,
+    pending_request_manifest:
+        HashMap<RequestId, oneshot::Sender<Result<FileManifest, Box<dyn Error + Send>>>>,
+    pending_request_chunk: HashMap<RequestId, oneshot::Sender<Result<Vec<u8>, Box<dyn Error + Send>>>>,
+    providing_files: HashMap<String, ProvidedFile>,
+    // 从公告广播中学到的提供者，`GetProviders`会优先查它，而不是每次都去查询DHT
+    provider_cache: HashMap<String, HashSet<PeerId>>,
+    // 从签名公告中学到的文件名到内容哈希的映射。DHT按内容哈希寻址，
+    // 这份映射是请求方校验对端清单时唯一可独立信赖的根哈希来源
+    known_hashes: HashMap<String, [u8; 32]>,
+    announcements_topic: IdentTopic,
+}
+
+impl EventLoop {
+    pub fn new(
+        swarm: Swarm<ComposedBehaviour>,
+        command_receiver: mpsc::Receiver<Command>,
+        event_sender: mpsc::Sender<Event>,
+    ) -> Self {
+        Self {
+            swarm,
+            command_receiver,
+            event_sender,
+            pending_dial: Default::default(),
+            pending_start_providing: Default::default(),
+            pending_get_providers: Default::default(),
+            pending_request_manifest: Default::default(),
+            pending_request_chunk: Default::default(),
+            providing_files: Default::default(),
+            provider_cache: Default::default(),
+            known_hashes: Default::default(),
+            announcements_topic: IdentTopic::new(ANNOUNCEMENTS_TOPIC),
+        }
+    }
+
+    // 持续运行事件循环，直到命令通道被关闭
+    pub async fn run(mut self) {
+        loop {
+            tokio::select! {
+                event = self.swarm.select_next_some() => self.handle_event(event).await,
+                command = self.command_receiver.recv() => match command {
+                    Some(c) => self.handle_command(c).await,
+                    // Client被丢弃后命令通道关闭，事件循环随之退出
+                    None => return,
+                },
+            }
+        }
+    }
+
+    async fn handle_event(
+        &mut self,
+        event: SwarmEvent<ComposedEvent, impl std::error::Error>,
+    ) {
+        match event {
+            SwarmEvent::Behaviour(ComposedEvent::Kademlia(event)) => {
+                self.handle_kademlia_event(event).await
+            }
+            SwarmEvent::Behaviour(ComposedEvent::Control(event)) => {
+                self.handle_control_event(event).await
+            }
+            SwarmEvent::Behaviour(ComposedEvent::Data(event)) => {
+                self.handle_data_event(event).await
+            }
+            SwarmEvent::Behaviour(ComposedEvent::Mdns(event)) => {
+                self.handle_mdns_event(event)
+            }
+            SwarmEvent::Behaviour(ComposedEvent::Gossipsub(event)) => {
+                self.handle_gossipsub_event(event)
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), .. } => {
+                if let Some(sender) = self.pending_dial.remove(&peer_id) {
+                    let _ = sender.send(Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        "dial failed",
+                    ))));
+                }
+            }
+            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                if let Some(sender) = self.pending_dial.remove(&peer_id) {
+                    let _ = sender.send(Ok(()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_kademlia_event(&mut self, event: KademliaEvent) {
+        match event {
+            KademliaEvent::OutboundQueryProgressed {
+                id,
+                result: QueryResult::StartProviding(_),
+                ..
+            } => {
+                if let Some(sender) = self.pending_start_providing.remove(&id) {
+                    let _ = sender.send(());
+                }
+            }
+            KademliaEvent::OutboundQueryProgressed {
+                id,
+                result: QueryResult::GetProviders(Ok(GetProvidersOk::FoundProviders { providers, .. })),
+                ..
+            } => {
+                if let Some((sender, root_hash)) = self.pending_get_providers.remove(&id) {
+                    let _ = sender.send((providers, root_hash));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // 控制面：清单请求/响应。体积小，不与批量分片传输共用连接上的同一个子流。
+    async fn handle_control_event(&mut self, event: RequestResponseEvent<ControlRequest, ControlResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                message:
+                    RequestResponseMessage::Request {
+                        request: ControlRequest::Manifest(file_name),
+                        channel,
+                        ..
+                    },
+                ..
+            } => {
+                let _ = self
+                    .event_sender
+                    .send(Event::InboundRequest { file_name: file_name.clone() })
+                    .await;
+
+                if let Some(file) = self.providing_files.get(&file_name) {
+                    let _ = self.swarm.behaviour_mut().control.send_response(
+                        channel,
+                        ControlResponse::Manifest(file.manifest.clone()),
+                    );
+                }
+            }
+            RequestResponseEvent::Message {
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response: ControlResponse::Manifest(manifest),
+                    },
+                ..
+            } => {
+                if let Some(sender) = self.pending_request_manifest.remove(&request_id) {
+                    let _ = sender.send(Ok(manifest));
+                }
+            }
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => {
+                if let Some(sender) = self.pending_request_manifest.remove(&request_id) {
+                    let _ = sender.send(Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        format!("{error:?}"),
+                    ))));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // mDNS：在局域网中发现/失效的节点直接同步给Kademlia的路由表，
+    // 这样无需任何bootstrap节点，同一局域网内的节点也能互相找到对方。
+    fn handle_mdns_event(&mut self, event: MdnsEvent) {
+        match event {
+            MdnsEvent::Discovered(list) => {
+                for (peer_id, multiaddr) in list {
+                    self.swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .add_address(&peer_id, multiaddr);
+                }
+            }
+            MdnsEvent::Expired(list) => {
+                for (peer_id, multiaddr) in list {
+                    self.swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .remove_address(&peer_id, &multiaddr);
+                }
+            }
+        }
+    }
+
+    // gossipsub：收到其他节点广播的文件可用性公告后，记入本地缓存，
+    // 供`GetProviders`优先查询，免去一次DHT往返；公告中的`root_hash`经发布者签名，
+    // 是请求方在信任对端清单之前唯一可独立依赖的根哈希来源。
+    fn handle_gossipsub_event(&mut self, event: GossipsubEvent) {
+        if let GossipsubEvent::Message { message, .. } = event {
+            if let Ok(announcement) = Announcement::decode(&message.data) {
+                self.provider_cache
+                    .entry(announcement.file_name.clone())
+                    .or_default()
+                    .insert(announcement.provider_peer_id);
+                self.known_hashes
+                    .insert(announcement.file_name, announcement.root_hash);
+            }
+        }
+    }
+
+    // 数据面：分片请求/响应。走独立的协议/子流，不会被控制面请求排队阻塞，反之亦然。
+    async fn handle_data_event(&mut self, event: RequestResponseEvent<ChunkRequest, ChunkResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                message:
+                    RequestResponseMessage::Request {
+                        request: ChunkRequest { file_name, index },
+                        channel,
+                        ..
+                    },
+                ..
+            } => {
+                let _ = self
+                    .event_sender
+                    .send(Event::InboundRequest { file_name: file_name.clone() })
+                    .await;
+
+                let response = self.providing_files.get(&file_name).and_then(|f| {
+                    let start = index as usize * f.manifest.chunk_size as usize;
+                    let end = (start + f.manifest.chunk_size as usize).min(f.data.len());
+                    (start < f.data.len() || (f.data.is_empty() && index == 0)).then(|| ChunkResponse {
+                        index,
+                        data: f.data[start..end].to_vec(),
+                        compression: f.manifest.compression,
+                    })
+                });
+
+                if let Some(response) = response {
+                    let _ = self.swarm.behaviour_mut().data.send_response(channel, response);
+                }
+            }
+            RequestResponseEvent::Message {
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response: ChunkResponse { data, .. },
+                    },
+                ..
+            } => {
+                if let Some(sender) = self.pending_request_chunk.remove(&request_id) {
+                    let _ = sender.send(Ok(data));
+                }
+            }
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => {
+                if let Some(sender) = self.pending_request_chunk.remove(&request_id) {
+                    let _ = sender.send(Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        format!("{error:?}"),
+                    ))));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::StartListening { addr, sender } => {
+                let _ = match self.swarm.listen_on(addr) {
+                    Ok(_) => sender.send(Ok(())),
+                    Err(e) => sender.send(Err(Box::new(e))),
+                };
+            }
+            Command::Dial {
+                peer_id,
+                peer_addr,
+                sender,
+            } => {
+                if self.pending_dial.contains_key(&peer_id) {
+                    return;
+                }
+
+                self.swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(&peer_id, peer_addr.clone());
+
+                let peer_addr = peer_addr.with(Protocol::P2p(peer_id.into()));
+                match self.swarm.dial(peer_addr) {
+                    Ok(()) => {
+                        self.pending_dial.insert(peer_id, sender);
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(Box::new(e)));
+                    }
+                }
+            }
+            Command::StartProviding {
+                file_name,
+                data,
+                compression,
+                sender,
+            } => {
+                let manifest = build_manifest(&data, compression);
+                let root_hash = manifest.root_hash;
+                self.known_hashes.insert(file_name.clone(), root_hash);
+                self.providing_files
+                    .insert(file_name.clone(), ProvidedFile { data, manifest });
+
+                // 以内容哈希而非文件名作为DHT的键：提供者记录因此是内容寻址的，
+                // 请求方据此（而不是按文件名）才能找到“持有这份确切字节”的节点。
+                let query_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .start_providing(root_hash.to_vec().into())
+                    .expect("No store error.");
+                self.pending_start_providing.insert(query_id, sender);
+
+                // 顺带向公告主题广播一下，订阅了的节点能立刻知道，不必等DHT查询；
+                // 公告经发布者签名，root_hash因此是请求方核对对端清单的独立依据
+                let announcement = Announcement {
+                    file_name,
+                    provider_peer_id: *self.swarm.local_peer_id(),
+                    root_hash,
+                };
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .publish(self.announcements_topic.clone(), announcement.encode());
+            }
+            Command::GetProviders { file_name, sender } => {
+                // 没有任何签名公告告诉过我们这个文件名对应的内容哈希，就无法发起
+                // 内容寻址的DHT查询——按文件名直接查找已经随chunk0-6被放弃，
+                // 调用方需要先`subscribe_announcements`并等待至少一次公告
+                let root_hash = match self.known_hashes.get(&file_name) {
+                    Some(hash) => *hash,
+                    None => {
+                        let _ = sender.send((HashSet::new(), None));
+                        return;
+                    }
+                };
+
+                // 本地公告缓存优先：命中的话不用再发起一次DHT查询
+                if let Some(cached) = self.provider_cache.get(&file_name) {
+                    if !cached.is_empty() {
+                        let _ = sender.send((cached.clone(), Some(root_hash)));
+                        return;
+                    }
+                }
+
+                let query_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_providers(root_hash.to_vec().into());
+                self.pending_get_providers
+                    .insert(query_id, (sender, Some(root_hash)));
+            }
+            Command::SubscribeAnnouncements { sender } => {
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .subscribe(&self.announcements_topic);
+                let _ = sender.send(());
+            }
+            Command::RequestManifest {
+                file_name,
+                peer,
+                sender,
+            } => {
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .control
+                    .send_request(&peer, ControlRequest::Manifest(file_name));
+                self.pending_request_manifest.insert(request_id, sender);
+            }
+            Command::RequestChunk {
+                file_name,
+                peer,
+                index,
+                sender,
+            } => {
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .data
+                    .send_request(&peer, ChunkRequest { file_name, index });
+                self.pending_request_chunk.insert(request_id, sender);
+            }
+        }
+    }
+}
@@ -1,16 +1,20 @@
 pub mod behaviour;
 pub mod event;
 pub mod protocol;
+pub mod transport;
 
 use std::{error::Error, iter};
 
 use libp2p::{
+    gossipsub::{Gossipsub, GossipsubConfig, MessageAuthenticity},
     identity::{self, ed25519},
     kad::{store::MemoryStore, Kademlia},
+    mdns::Mdns,
     request_response::{ProtocolSupport, RequestResponse},
     swarm::SwarmBuilder,
 };
 pub use protocol::*;
+pub use transport::TransportConfig;
 use tokio::sync::mpsc::{self, Receiver};
 
 use crate::client::Client;
@@ -27,11 +31,15 @@ use self::{
 //
 // 参数:
 // - secret_key_seed: 用于生成密钥对的种子。如果未提供，则自动生成密钥对。
+// - enable_mdns: 是否启用mDNS局域网节点发现，关闭时只能通过显式dial/bootstrap找到对方。
+// - transport_config: 启用哪些底层传输协议（TCP、QUIC），参见`TransportConfig`。
 //
 // 返回值:
 // - Result: 包含客户端、事件接收器和事件循环系统的元组，或错误信息。
 pub async fn new(
     secret_key_seed: Option<u8>,
+    enable_mdns: bool,
+    transport_config: TransportConfig,
 ) -> Result<(Client, Receiver<Event>, EventLoop), Box<dyn Error>> {
 
     // 创建密钥对
@@ -52,27 +60,51 @@ pub async fn new(
     // 节点ID是网络中唯一标识一个节点的标识符，由公钥派生而来。
     let peer_id = id_keys.public().to_peer_id();
 
+    // gossipsub需要签名用的密钥，要在id_keys被传输层消费之前先克隆一份
+    let gossipsub_keys = id_keys.clone();
+
     // 构建网络层管理组件Swarm
-    // Swarm是Libp2p中用于管理网络连接的核心组件。这里我们构建了一个包含Kademlia和RequestResponse行为的Swarm。
-    let transport = libp2p::development_transport(id_keys).await?;
-
-    // RequestResponse是Libp2p中的一个请求-响应协议，用于在网络中进行双向通信。
-    let request_response = RequestResponse::new(
-        // 自定义的文件交换协议，用于在网络中传输文件。
-        FileExchangeCodec(),
-        // 支持的协议列表，这里只支持文件交换协议。
-        iter::once((FileExchangeProtocol(), ProtocolSupport::Full)),
-        // 自定义的事件处理器，用于处理网络事件。
+    // Swarm是Libp2p中用于管理网络连接的核心组件。这里我们构建了一个包含Kademlia、控制面和数据面行为的Swarm。
+    // 按照`transport_config`组合TCP（Noise+Yamux）和/或QUIC，而不是固定写死的开发用传输。
+    let transport = transport::build_transport(id_keys, transport_config);
+
+    // 控制面：清单等轻量请求单独走一个协议，独立于批量数据传输
+    let control = RequestResponse::new(
+        ControlCodec(),
+        iter::once((ControlProtocol(), ProtocolSupport::Full)),
+        Default::default(),
+    );
+
+    // 数据面：分片等批量传输单独走一个协议，libp2p会为它开启独立的子流，
+    // 这样一次多兆字节的传输不会挤占控制面的往返时延。
+    let data = RequestResponse::new(
+        DataCodec(),
+        iter::once((DataProtocol(), ProtocolSupport::Full)),
         Default::default(),
     );
 
     // Kademlia是Libp2p中的一个分布式哈希表协议，用于查找和维护网络中的节点。
     let kademlia = Kademlia::new(peer_id, MemoryStore::new(peer_id));
 
+    // 按需启用mDNS，零配置发现同一局域网内的其他节点
+    let mdns = if enable_mdns {
+        Some(Mdns::new(Default::default()).await?)
+    } else {
+        None
+    }
+    .into();
+
+    // gossipsub用于广播文件可用性公告，消息以节点身份签名，防止被随意冒充
+    let gossipsub = Gossipsub::new(MessageAuthenticity::Signed(gossipsub_keys), GossipsubConfig::default())
+        .expect("Valid gossipsub config.");
+
     // 构建自定义的网络行为
     let behaviour = ComposedBehaviour {
+        control,
+        data,
         kademlia,
-        request_response,
+        mdns,
+        gossipsub,
     };
 
     // 创建网络层管理组件Swarm
@@ -90,7 +122,7 @@ pub async fn new(
 
     // 返回客户端、事件接收器和事件循环系统的元组
     Ok((
-        Client::new(command_sender),
+        Client::new(command_sender, event_sender.clone()),
         event_receiver,
         EventLoop::new(swarm, command_receiver, event_sender),
     ))
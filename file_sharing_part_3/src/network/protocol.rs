@@ -6,117 +6,600 @@ use libp2p::{
         ProtocolName,
     },
     request_response::RequestResponseCodec,
+    PeerId,
 };
 
+// 单个分片的目标大小（字节）。文件按该大小切分后逐片传输，不再受限于单帧大小
+pub const CHUNK_SIZE: u32 = 256 * 1024;
+
+// 单帧允许读取的最大字节数，需要容纳一个分片以及少量头部/压缩膨胀的余量
+const MAX_FRAME_SIZE: usize = CHUNK_SIZE as usize * 2 + 1024;
+
+// 清单的体积随分片数量增长（每个分片一个32字节的默克尔叶子哈希），与单个分片的大小无关，
+// 所以控制面需要一个独立于数据面`MAX_FRAME_SIZE`的上限。按最多65536个分片留出余量——
+// 默认256KiB的分片大小下，这对应约16GiB的文件——超过此数量的清单直接视为异常。
+const MAX_MANIFEST_CHUNK_HASHES: usize = 65_536;
+const MAX_CONTROL_FRAME_SIZE: usize = MAX_MANIFEST_CHUNK_HASHES * 32 + 1024;
+
+// 控制面协议：负责清单这类体积小、要求低延迟的请求，不与大块数据传输共用同一协议，
+// 这样批量传输占满连接时不会拖慢DHT/元数据相关的往返。
 #[derive(Debug, Clone)]
+pub struct ControlProtocol();
 
-// 
-pub struct FileExchangeProtocol();
+#[derive(Clone)]
+pub struct ControlCodec();
 
+// 数据面协议：负责分片这类体积大的批量传输。
+#[derive(Debug, Clone)]
+pub struct DataProtocol();
 
 #[derive(Clone)]
-pub struct FileExchangeCodec();
+pub struct DataCodec();
+
+// 分片在线上传输时使用的压缩方式，由清单声明后对该文件的所有分片生效
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Zstd,
+}
+
+impl Compression {
+    fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Zstd),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown compression tag",
+            )),
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Zstd => zstd::stream::encode_all(data, 0),
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Zstd => zstd::stream::decode_all(data),
+        }
+    }
+}
+
+// 文件的整体描述清单，请求方在拉取任何分片之前先通过控制面获取它。
+// `root_hash`与`chunk_hashes`共同构成一棵以BLAKE3为叶子哈希的默克尔树，
+// 使内容可以被独立验证：每收到一个分片就能立刻核对，而不必等到整个文件下载完。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileManifest {
+    pub total_size: u64,
+    pub chunk_size: u32,
+    pub chunk_count: u32,
+    // 默克尔树的叶子：每个分片（压缩前的原始内容）的BLAKE3哈希，下标与分片序号一一对应
+    pub chunk_hashes: Vec<[u8; 32]>,
+    // 默克尔树的根，即内容地址——等价于把所有叶子两两哈希归并到只剩一个节点
+    pub root_hash: [u8; 32],
+    pub compression: Compression,
+}
+
+// 计算一棵以BLAKE3为叶子哈希的默克尔树的根。叶子数为奇数时，最后一个节点与自身配对，
+// 这是默克尔树实现里常见的做法，避免专门处理“落单”的节点。
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return *blake3::hash(&[]).as_bytes();
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        let mut next = Vec::with_capacity((level.len() + 1) / 2);
+        for pair in level.chunks(2) {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(&pair[0]);
+            hasher.update(pair.get(1).unwrap_or(&pair[0]));
+            next.push(*hasher.finalize().as_bytes());
+        }
+        level = next;
+    }
+    level[0]
+}
+
+// gossipsub上广播文件可用性公告所使用的主题名
+pub const ANNOUNCEMENTS_TOPIC: &str = "announcements";
+
+// 节点开始提供某个文件时，向`ANNOUNCEMENTS_TOPIC`广播的公告内容。
+// gossipsub消息以发布者的节点身份签名（见`network::new`），所以`root_hash`是请求方
+// 能够独立核对清单的第一手来源——不同于控制面清单响应，后者来自将要被验证的同一个对端。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    pub file_name: String,
+    pub provider_peer_id: PeerId,
+    pub root_hash: [u8; 32],
+}
+
+impl Announcement {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_bytes(&mut buf, self.file_name.as_bytes());
+        write_bytes(&mut buf, &self.provider_peer_id.to_bytes());
+        buf.extend_from_slice(&self.root_hash);
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut cursor = 0usize;
+        let file_name = read_string(bytes, &mut cursor)?;
+        let peer_bytes = read_bytes(bytes, &mut cursor)?;
+        let provider_peer_id = PeerId::from_bytes(&peer_bytes)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid peer id"))?;
+        let root_hash = read_hash(bytes, &mut cursor)?;
+        Ok(Announcement {
+            file_name,
+            provider_peer_id,
+            root_hash,
+        })
+    }
+}
+
+// 控制面请求：目前只有索要清单一种
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlRequest {
+    Manifest(String),
+}
 
-// 传输数据的编解码方式
-pub struct FileRequest(pub String);
+// 控制面响应
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlResponse {
+    Manifest(FileManifest),
+}
+
+// 数据面请求：索要某个具体分片
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkRequest {
+    pub file_name: String,
+    pub index: u32,
+}
 
-// 传输数据的编解码方式
+// 数据面响应：携带原始（已解压）字节及其压缩方式
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct FileResponse(pub String);
+pub struct ChunkResponse {
+    pub index: u32,
+    pub data: Vec<u8>,
+    pub compression: Compression,
+}
+
+// 定义控制面协议名称
+impl ProtocolName for ControlProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        "/file-control/1".as_bytes()
+    }
+}
 
-// 定义协议名称
-impl ProtocolName for FileExchangeProtocol {
-    
-    // 返回协议名称
+// 定义数据面协议名称
+impl ProtocolName for DataProtocol {
     fn protocol_name(&self) -> &[u8] {
-        "/file-exchange/1".as_bytes()
+        "/file-data/1".as_bytes()
+    }
+}
+
+impl ControlRequest {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            ControlRequest::Manifest(file_name) => {
+                buf.push(0);
+                write_bytes(&mut buf, file_name.as_bytes());
+            }
+        }
+        buf
     }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut cursor = 0usize;
+        match read_u8(bytes, &mut cursor)? {
+            0 => Ok(ControlRequest::Manifest(read_string(bytes, &mut cursor)?)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown ControlRequest tag",
+            )),
+        }
+    }
+}
+
+impl ControlResponse {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            ControlResponse::Manifest(manifest) => {
+                buf.push(0);
+                buf.extend_from_slice(&manifest.total_size.to_le_bytes());
+                buf.extend_from_slice(&manifest.chunk_size.to_le_bytes());
+                buf.extend_from_slice(&manifest.chunk_count.to_le_bytes());
+                buf.extend_from_slice(&manifest.root_hash);
+                buf.extend_from_slice(&(manifest.chunk_hashes.len() as u32).to_le_bytes());
+                for hash in &manifest.chunk_hashes {
+                    buf.extend_from_slice(hash);
+                }
+                buf.push(manifest.compression.to_byte());
+            }
+        }
+        buf
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut cursor = 0usize;
+        match read_u8(bytes, &mut cursor)? {
+            0 => {
+                let total_size = read_u64(bytes, &mut cursor)?;
+                let chunk_size = read_u32(bytes, &mut cursor)?;
+                let chunk_count = read_u32(bytes, &mut cursor)?;
+                let root_hash = read_hash(bytes, &mut cursor)?;
+                let hash_count = read_u32(bytes, &mut cursor)? as usize;
+                // 不按对方声明的hash_count预分配容量：恶意的声明值可能远大于实际剩余
+                // 字节数，提前reserve会在读到真正的越界错误之前就尝试一次巨额分配。
+                // 逐个读取，让`read_hash`在数据读完时以错误的形式提前终止。
+                let mut chunk_hashes = Vec::new();
+                for _ in 0..hash_count {
+                    chunk_hashes.push(read_hash(bytes, &mut cursor)?);
+                }
+                if chunk_hashes.len() != chunk_count as usize {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "chunk_count does not match number of chunk hashes",
+                    ));
+                }
+                let compression = Compression::from_byte(read_u8(bytes, &mut cursor)?)?;
+                Ok(ControlResponse::Manifest(FileManifest {
+                    total_size,
+                    chunk_size,
+                    chunk_count,
+                    chunk_hashes,
+                    root_hash,
+                    compression,
+                }))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown ControlResponse tag",
+            )),
+        }
+    }
+}
+
+impl ChunkRequest {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_bytes(&mut buf, self.file_name.as_bytes());
+        buf.extend_from_slice(&self.index.to_le_bytes());
+        buf
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut cursor = 0usize;
+        let file_name = read_string(bytes, &mut cursor)?;
+        let index = read_u32(bytes, &mut cursor)?;
+        Ok(ChunkRequest { file_name, index })
+    }
+}
+
+impl ChunkResponse {
+    fn encode(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.index.to_le_bytes());
+        buf.push(self.compression.to_byte());
+        let payload = self.compression.compress(&self.data)?;
+        write_bytes(&mut buf, &payload);
+        Ok(buf)
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut cursor = 0usize;
+        let index = read_u32(bytes, &mut cursor)?;
+        let compression = Compression::from_byte(read_u8(bytes, &mut cursor)?)?;
+        let payload = read_bytes(bytes, &mut cursor)?;
+        let data = compression.decompress(&payload)?;
+        Ok(ChunkResponse {
+            index,
+            data,
+            compression,
+        })
+    }
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn read_u8(bytes: &[u8], cursor: &mut usize) -> io::Result<u8> {
+    let byte = *bytes
+        .get(*cursor)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated frame"))?;
+    *cursor += 1;
+    Ok(byte)
 }
 
-// 传输数据的编解码方式
+fn read_u32(bytes: &[u8], cursor: &mut usize) -> io::Result<u32> {
+    let slice = bytes
+        .get(*cursor..*cursor + 4)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated frame"))?;
+    *cursor += 4;
+    Ok(u32::from_le_bytes(slice.try_into().unwrap()))
+}
+
+fn read_u64(bytes: &[u8], cursor: &mut usize) -> io::Result<u64> {
+    let slice = bytes
+        .get(*cursor..*cursor + 8)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated frame"))?;
+    *cursor += 8;
+    Ok(u64::from_le_bytes(slice.try_into().unwrap()))
+}
+
+fn read_hash(bytes: &[u8], cursor: &mut usize) -> io::Result<[u8; 32]> {
+    let slice = bytes
+        .get(*cursor..*cursor + 32)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated frame"))?;
+    *cursor += 32;
+    Ok(slice.try_into().unwrap())
+}
+
+fn read_bytes(bytes: &[u8], cursor: &mut usize) -> io::Result<Vec<u8>> {
+    let len = read_u32(bytes, cursor)? as usize;
+    let slice = bytes
+        .get(*cursor..*cursor + len)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated frame"))?;
+    *cursor += len;
+    Ok(slice.to_vec())
+}
+
+fn read_string(bytes: &[u8], cursor: &mut usize) -> io::Result<String> {
+    let raw = read_bytes(bytes, cursor)?;
+    String::from_utf8(raw)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "file name is not valid utf-8"))
+}
+
+// 控制面编解码：负责清单这类轻量请求
 #[async_trait]
-impl RequestResponseCodec for FileExchangeCodec {
-    type Protocol = FileExchangeProtocol;
-    type Request = FileRequest;
-    type Response = FileResponse;
+impl RequestResponseCodec for ControlCodec {
+    type Protocol = ControlProtocol;
+    type Request = ControlRequest;
+    type Response = ControlResponse;
 
-    // 读请求
-    async fn read_request<T>(
-        &mut self,
-        _: &FileExchangeProtocol,
-        io: &mut T,
-    ) -> io::Result<Self::Request>
+    async fn read_request<T>(&mut self, _: &ControlProtocol, io: &mut T) -> io::Result<Self::Request>
     where
         T: AsyncRead + Unpin + Send,
     {
-        // 读取固定长度的字节
-        let vec = read_length_prefixed(io, 1_000_000).await?;
+        let vec = read_length_prefixed(io, MAX_CONTROL_FRAME_SIZE).await?;
+        if vec.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        ControlRequest::decode(&vec)
+    }
 
-        // 检查是否为空
+    async fn read_response<T>(&mut self, _: &ControlProtocol, io: &mut T) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let vec = read_length_prefixed(io, MAX_CONTROL_FRAME_SIZE).await?;
         if vec.is_empty() {
             return Err(io::ErrorKind::UnexpectedEof.into());
         }
+        ControlResponse::decode(&vec)
+    }
 
-        Ok(FileRequest(String::from_utf8(vec).unwrap()))
+    async fn write_request<T>(
+        &mut self,
+        _: &ControlProtocol,
+        io: &mut T,
+        request: ControlRequest,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, request.encode()).await?;
+        io.close().await?;
+        Ok(())
     }
 
-    // 读取响应
-    async fn read_response<T>(
+    async fn write_response<T>(
         &mut self,
-        _: &FileExchangeProtocol,
+        _: &ControlProtocol,
         io: &mut T,
-    ) -> io::Result<Self::Response>
+        response: ControlResponse,
+    ) -> io::Result<()>
     where
-        T: AsyncRead + Unpin + Send,
+        T: AsyncWrite + Unpin + Send,
     {
-        // 读取固定长度的字节
-        let vec = read_length_prefixed(io, 1_000_000).await?;
+        write_length_prefixed(io, response.encode()).await?;
+        io.close().await?;
+        Ok(())
+    }
+}
+
+// 数据面编解码：负责分片这类批量传输，走独立的libp2p子流，不会与控制面排队
+#[async_trait]
+impl RequestResponseCodec for DataCodec {
+    type Protocol = DataProtocol;
+    type Request = ChunkRequest;
+    type Response = ChunkResponse;
 
-        // 检查是否为空
+    async fn read_request<T>(&mut self, _: &DataProtocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let vec = read_length_prefixed(io, MAX_FRAME_SIZE).await?;
         if vec.is_empty() {
             return Err(io::ErrorKind::UnexpectedEof.into());
         }
+        ChunkRequest::decode(&vec)
+    }
 
-        Ok(FileResponse(String::from_utf8(vec).unwrap()))
+    async fn read_response<T>(&mut self, _: &DataProtocol, io: &mut T) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let vec = read_length_prefixed(io, MAX_FRAME_SIZE).await?;
+        if vec.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        ChunkResponse::decode(&vec)
     }
 
-    // 写请求
     async fn write_request<T>(
         &mut self,
-        _: &FileExchangeProtocol,
+        _: &DataProtocol,
         io: &mut T,
-        FileRequest(data): FileRequest,
+        request: ChunkRequest,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
-        // 写入数据的长度
-        write_length_prefixed(io, data).await?;
-
-        // 关闭连接
+        write_length_prefixed(io, request.encode()).await?;
         io.close().await?;
-
         Ok(())
     }
 
-    // 写响应
     async fn write_response<T>(
         &mut self,
-        _: &FileExchangeProtocol,
+        _: &DataProtocol,
         io: &mut T,
-        FileResponse(data): FileResponse,
+        response: ChunkResponse,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
+        // 分片会按照声明的压缩方式被压缩后再写出
+        write_length_prefixed(io, response.encode()?).await?;
+        io.close().await?;
+        Ok(())
+    }
+}
 
-        // 写入数据的长度
-        write_length_prefixed(io, data).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // 关闭连接
-        io.close().await?;
+    #[test]
+    fn merkle_root_empty_is_hash_of_empty_input() {
+        assert_eq!(merkle_root(&[]), *blake3::hash(&[]).as_bytes());
+    }
 
-        Ok(())
+    #[test]
+    fn merkle_root_single_leaf_is_itself() {
+        let leaf = *blake3::hash(b"single chunk").as_bytes();
+        assert_eq!(merkle_root(&[leaf]), leaf);
+    }
+
+    #[test]
+    fn merkle_root_even_leaf_count_is_order_sensitive() {
+        let a = *blake3::hash(b"a").as_bytes();
+        let b = *blake3::hash(b"b").as_bytes();
+        assert_eq!(merkle_root(&[a, b]), merkle_root(&[a, b]));
+        assert_ne!(merkle_root(&[a, b]), merkle_root(&[b, a]));
+    }
+
+    #[test]
+    fn merkle_root_odd_leaf_count_pairs_last_node_with_itself() {
+        let a = *blake3::hash(b"a").as_bytes();
+        let b = *blake3::hash(b"b").as_bytes();
+        let c = *blake3::hash(b"c").as_bytes();
+        // 落单的最后一个叶子（c）与自身配对，而不是panic或被忽略
+        let root = merkle_root(&[a, b, c]);
+        assert_eq!(root, merkle_root(&[a, b, c, c]));
+        assert_ne!(root, merkle_root(&[a, b]));
+    }
+
+    #[test]
+    fn control_request_roundtrip() {
+        let request = ControlRequest::Manifest("foo.txt".to_string());
+        let decoded = ControlRequest::decode(&request.encode()).unwrap();
+        assert_eq!(request, decoded);
+    }
+
+    #[test]
+    fn control_response_roundtrip() {
+        let manifest = FileManifest {
+            total_size: 10,
+            chunk_size: 4,
+            chunk_count: 3,
+            chunk_hashes: vec![[1u8; 32], [2u8; 32], [3u8; 32]],
+            root_hash: merkle_root(&[[1u8; 32], [2u8; 32], [3u8; 32]]),
+            compression: Compression::Zstd,
+        };
+        let response = ControlResponse::Manifest(manifest.clone());
+        let decoded = ControlResponse::decode(&response.encode()).unwrap();
+        assert_eq!(response, decoded);
+        match decoded {
+            ControlResponse::Manifest(decoded_manifest) => assert_eq!(decoded_manifest, manifest),
+        }
+    }
+
+    #[test]
+    fn control_response_rejects_chunk_count_mismatch() {
+        // chunk_count声明为3，但只编码2个哈希——手工拼出这样一帧不一致的字节
+        let mut buf = Vec::new();
+        buf.push(0u8); // Manifest tag
+        buf.extend_from_slice(&10u64.to_le_bytes()); // total_size
+        buf.extend_from_slice(&4u32.to_le_bytes()); // chunk_size
+        buf.extend_from_slice(&3u32.to_le_bytes()); // chunk_count
+        buf.extend_from_slice(&[0u8; 32]); // root_hash
+        buf.extend_from_slice(&2u32.to_le_bytes()); // hash_count (与chunk_count不符)
+        buf.extend_from_slice(&[1u8; 32]);
+        buf.extend_from_slice(&[2u8; 32]);
+        buf.push(Compression::None.to_byte());
+
+        assert!(ControlResponse::decode(&buf).is_err());
+    }
+
+    #[test]
+    fn chunk_request_roundtrip() {
+        let request = ChunkRequest {
+            file_name: "foo.txt".to_string(),
+            index: 7,
+        };
+        let decoded = ChunkRequest::decode(&request.encode()).unwrap();
+        assert_eq!(request, decoded);
+    }
+
+    #[test]
+    fn chunk_response_roundtrip_none_compression() {
+        let response = ChunkResponse {
+            index: 1,
+            data: vec![1, 2, 3, 4, 5],
+            compression: Compression::None,
+        };
+        let decoded = ChunkResponse::decode(&response.encode().unwrap()).unwrap();
+        assert_eq!(response, decoded);
+    }
+
+    #[test]
+    fn chunk_response_roundtrip_zstd_compression() {
+        let response = ChunkResponse {
+            index: 2,
+            data: vec![42; 1024],
+            compression: Compression::Zstd,
+        };
+        let decoded = ChunkResponse::decode(&response.encode().unwrap()).unwrap();
+        assert_eq!(response, decoded);
+    }
+
+    #[test]
+    fn announcement_roundtrip() {
+        let announcement = Announcement {
+            file_name: "foo.txt".to_string(),
+            provider_peer_id: PeerId::random(),
+            root_hash: [7u8; 32],
+        };
+        let decoded = Announcement::decode(&announcement.encode()).unwrap();
+        assert_eq!(announcement, decoded);
     }
 }
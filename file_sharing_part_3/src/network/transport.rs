@@ -0,0 +1,77 @@
+use libp2p::{
+    core::{muxing::StreamMuxerBox, transport::OrTransport, upgrade},
+    identity, noise, quic,
+    tcp::{GenTcpConfig, TokioTcpTransport},
+    yamux, PeerId, Transport,
+};
+
+// 启用哪些底层传输协议，可以同时启用多个，构建时用`OrTransport`组合起来。
+// 节点因此能够同时在`/ip4/.../tcp/...`和`/ip4/.../udp/.../quic-v1`两种多地址上被拨通。
+#[derive(Debug, Clone, Copy)]
+pub struct TransportConfig {
+    pub tcp: bool,
+    pub quic: bool,
+}
+
+impl Default for TransportConfig {
+    // 默认只启用TCP，与历史行为保持一致
+    fn default() -> Self {
+        TransportConfig {
+            tcp: true,
+            quic: false,
+        }
+    }
+}
+
+impl TransportConfig {
+    pub fn with_tcp(mut self, enabled: bool) -> Self {
+        self.tcp = enabled;
+        self
+    }
+
+    pub fn with_quic(mut self, enabled: bool) -> Self {
+        self.quic = enabled;
+        self
+    }
+}
+
+// 根据配置构建传输层。
+// TCP分支手动搭建Noise握手 + Yamux多路复用（与chat示例中的写法一致）；
+// QUIC分支自带加密和多路复用，不需要额外升级。两者同时启用时用`OrTransport`组合，
+// 最终都装箱为统一的`StreamMuxerBox`传输。
+pub fn build_transport(
+    id_keys: identity::Keypair,
+    config: TransportConfig,
+) -> libp2p::core::transport::Boxed<(PeerId, StreamMuxerBox)> {
+    let tcp_transport = config.tcp.then(|| {
+        let noise_keys = noise::Keypair::<noise::X25519Spec>::new()
+            .into_authentic(&id_keys)
+            .expect("Signing libp2p-noise static keypair failed.");
+
+        TokioTcpTransport::new(GenTcpConfig::default().nodelay(true))
+            .upgrade(upgrade::Version::V1)
+            .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+            .multiplex(yamux::YamuxConfig::default())
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed()
+    });
+
+    let quic_transport = config.quic.then(|| {
+        let quic_config = quic::Config::new(&id_keys);
+        quic::tokio::Transport::new(quic_config)
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed()
+    });
+
+    match (tcp_transport, quic_transport) {
+        (Some(tcp), Some(quic)) => OrTransport::new(quic, tcp)
+            .map(|either, _| match either {
+                libp2p::core::either::EitherOutput::First(output) => output,
+                libp2p::core::either::EitherOutput::Second(output) => output,
+            })
+            .boxed(),
+        (Some(tcp), None) => tcp,
+        (None, Some(quic)) => quic,
+        (None, None) => panic!("TransportConfig必须至少启用一种底层传输协议"),
+    }
+}